@@ -3,12 +3,12 @@ use std::fs;
 use std::io;
 pub struct Lexer {
     source: Vec<char>,
-    tokens: Vec<Token>,
+    pending: Option<Token>,
     start: usize,
     current: usize,
     line: usize,
     keywords: HashMap<String, TokenType>,
-    has_error: bool,
+    errors: Vec<Error>,
 }
 
 impl Lexer {
@@ -19,26 +19,54 @@ impl Lexer {
         keywords.insert(String::from("false"), TokenType::False);
         Lexer {
             source: source.chars().collect(),
-            tokens: Vec::new(),
+            pending: None,
             start: 0,
             current: 0,
             line: 1,
             keywords,
-            has_error: false,
+            errors: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> &Vec<Token> {
-        while !self.is_at_end() {
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn push_error(&mut self, kind: ErrorKind) {
+        self.errors.push(Error {
+            kind,
+            span: self.current_span(),
+        });
+    }
+
+    fn current_span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.current,
+            line: self.line,
+        }
+    }
+
+    /// Scans and returns the next token, skipping whitespace and recoverable
+    /// errors along the way. Produces an `Eof` token once the source is
+    /// exhausted, and keeps producing `Eof` on every call after that.
+    pub fn next_token(&mut self) -> Token {
+        loop {
+            if self.is_at_end() {
+                self.start = self.current;
+                return Token {
+                    token_type: TokenType::Eof,
+                    lexeme: String::from(""),
+                    literal: Value::Null,
+                    span: self.current_span(),
+                };
+            }
             self.start = self.current;
             self.scan_token();
+            if let Some(token) = self.pending.take() {
+                return token;
+            }
         }
-        self.tokens.push(Token {
-            token_type: TokenType::Eof,
-            lexeme: String::from(""),
-            literal: Value::Null,
-        });
-        &self.tokens
     }
 
     fn is_at_end(&self) -> bool {
@@ -60,7 +88,7 @@ impl Lexer {
             '"' => {
                 self.string();
             }
-            c if c.is_ascii_digit() => {
+            c if c.is_ascii_digit() || c == '-' => {
                 self.number();
             }
             ' ' | '\r' | '\t' => {}
@@ -68,16 +96,17 @@ impl Lexer {
                 self.identifier();
             }
 
-            _ => report("Unexpected character", &mut self.has_error),
+            _ => self.push_error(ErrorKind::UnexpectedChar),
         }
     }
 
     fn add_token(&mut self, token_type: TokenType, literal: Option<Value>) {
         let text: String = self.source[self.start..self.current].iter().collect();
-        self.tokens.push(Token {
+        self.pending = Some(Token {
             token_type,
             lexeme: String::from(text),
             literal: literal.unwrap_or(Value::Null),
+            span: self.current_span(),
         })
     }
 
@@ -94,33 +123,133 @@ impl Lexer {
         self.source[self.current]
     }
 
+    fn peek_next(&self) -> char {
+        if self.current + 1 >= self.source.len() {
+            return '\0';
+        }
+        self.source[self.current + 1]
+    }
+
     fn string(&mut self) {
+        let mut value = String::new();
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
+            let c = self.advance();
+            if c == '\n' {
                 self.line = self.line + 1;
+                value.push(c);
+                continue;
             }
-            self.advance();
+            if c == '\\' {
+                match self.escape_sequence() {
+                    Some(decoded) => value.push(decoded),
+                    None => return,
+                }
+                continue;
+            }
+            value.push(c);
         }
 
         if self.is_at_end() {
-            report("Unterminated string.", &mut self.has_error);
+            self.push_error(ErrorKind::UnterminatedString);
             return;
         }
         self.advance();
 
-        let literal = self.source[self.start + 1..self.current - 1]
-            .iter()
-            .collect();
-        self.add_token(TokenType::String, Some(Value::String(literal)));
+        self.add_token(TokenType::String, Some(Value::String(value)));
+    }
+
+    fn escape_sequence(&mut self) -> Option<char> {
+        if self.is_at_end() {
+            self.push_error(ErrorKind::UnterminatedString);
+            return None;
+        }
+        let c = self.advance();
+        match c {
+            '"' => Some('"'),
+            '\\' => Some('\\'),
+            '/' => Some('/'),
+            'b' => Some('\u{8}'),
+            'f' => Some('\u{c}'),
+            'n' => Some('\n'),
+            'r' => Some('\r'),
+            't' => Some('\t'),
+            'u' => self.unicode_escape(),
+            _ => {
+                self.push_error(ErrorKind::MalformedEscapeSequence);
+                None
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if (0xD800..=0xDBFF).contains(&high) {
+            if self.peek() == '\\' && self.peek_next() == 'u' {
+                self.advance();
+                self.advance();
+                let low = self.hex4()?;
+                if (0xDC00..=0xDFFF).contains(&low) {
+                    let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                    return char::from_u32(code);
+                }
+            }
+            self.push_error(ErrorKind::MalformedEscapeSequence);
+            return None;
+        }
+        match char::from_u32(high) {
+            Some(c) => Some(c),
+            None => {
+                self.push_error(ErrorKind::MalformedEscapeSequence);
+                None
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let mut value: u32 = 0;
+        for _ in 0..4 {
+            if self.is_at_end() {
+                self.push_error(ErrorKind::MalformedEscapeSequence);
+                return None;
+            }
+            match self.advance().to_digit(16) {
+                Some(d) => value = value * 16 + d,
+                None => {
+                    self.push_error(ErrorKind::MalformedEscapeSequence);
+                    return None;
+                }
+            }
+        }
+        Some(value)
     }
 
     fn number(&mut self) {
-        while self.peek().is_ascii_digit() && !self.is_at_end() {
+        while self.peek().is_ascii_digit() {
+            self.advance();
+        }
+
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
+            while self.peek().is_ascii_digit() {
+                self.advance();
+            }
         }
+
+        if self.peek() == 'e' || self.peek() == 'E' {
+            self.advance();
+            if self.peek() == '+' || self.peek() == '-' {
+                self.advance();
+            }
+            while self.peek().is_ascii_digit() {
+                self.advance();
+            }
+        }
+
         let string_digit: String = self.source[self.start..self.current].iter().collect();
-        let number = string_digit.parse::<i32>().expect("Unable to parse digit");
-        self.add_token(TokenType::Number, Some(Value::Number(number)))
+        match string_digit.parse::<f64>() {
+            Ok(number) => self.add_token(TokenType::Number, Some(Value::Number(number))),
+            Err(_) => self.push_error(ErrorKind::MalformedNumber),
+        }
     }
 
     fn identifier(&mut self) {
@@ -133,15 +262,88 @@ impl Lexer {
             Some(t) => {
                 self.add_token(t.clone(), None);
             }
-            None => report("Unexpected character.", &mut self.has_error),
+            None => self.push_error(ErrorKind::UnexpectedChar),
         }
     }
 }
 
+/// Wraps a `Lexer`, pulling tokens from it lazily and buffering a single
+/// token of lookahead so the parser never needs the whole source tokenized
+/// up front.
+pub struct TokenIterator {
+    lexer: Lexer,
+    peeked: Option<Token>,
+}
+
+impl TokenIterator {
+    pub fn new(lexer: Lexer) -> Self {
+        TokenIterator {
+            lexer,
+            peeked: None,
+        }
+    }
+
+    pub fn advance(&mut self) -> Token {
+        match self.peeked.take() {
+            Some(token) => token,
+            None => self.lexer.next_token(),
+        }
+    }
+
+    pub fn peek(&mut self) -> &Token {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lexer.next_token());
+        }
+        self.peeked.as_ref().unwrap()
+    }
+
+    pub fn current_span(&self) -> Span {
+        match &self.peeked {
+            Some(token) => token.span,
+            None => self.lexer.current_span(),
+        }
+    }
+
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        self.lexer.take_errors()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Value,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+impl Span {
+    /// Returns the full text of the line this span starts on, plus the
+    /// 1-based column where the span begins, so callers can render a
+    /// `line text` / `^` caret pair pointing at the offending token.
+    pub fn locate(&self, source: &str) -> (String, usize) {
+        let chars: Vec<char> = source.chars().collect();
+        let line_start = chars[..self.start]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let line_end = chars[line_start..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map(|offset| line_start + offset)
+            .unwrap_or(chars.len());
+        let line_text: String = chars[line_start..line_end].iter().collect();
+        let col = self.start - line_start + 1;
+        (line_text, col)
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -163,34 +365,228 @@ pub enum TokenType {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     String(String),
-    Number(i32),
+    Number(f64),
     Array(Vec<Value>),
     Bool(bool),
     Null,
     Object(HashMap<String, Value>),
 }
 
+/// Knobs for `Value::to_json_with`/`to_json_pretty_with`. The no-argument
+/// `to_json`/`to_json_pretty` methods use `SerializeOptions::default()`, so
+/// existing callers keep the same output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SerializeOptions {
+    /// Escape non-ASCII characters as `\uXXXX` (with surrogate pairs for
+    /// codepoints above the BMP) instead of emitting raw UTF-8.
+    pub escape_non_ascii: bool,
+    /// Append a trailing `\n` after pretty-printed output.
+    pub trailing_newline: bool,
+}
+
+impl Value {
+    pub fn to_json(&self) -> String {
+        self.to_json_with(&SerializeOptions::default())
+    }
+
+    pub fn to_json_with(&self, options: &SerializeOptions) -> String {
+        match self {
+            Value::Null => String::from("null"),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => format_number(*n),
+            Value::String(s) => format!("\"{}\"", escape_string(s, options)),
+            Value::Array(values) => {
+                let items: Vec<String> = values.iter().map(|v| v.to_json_with(options)).collect();
+                format!("[{}]", items.join(","))
+            }
+            Value::Object(pairs) => {
+                let items: Vec<String> = pairs
+                    .iter()
+                    .map(|(k, v)| {
+                        format!(
+                            "\"{}\":{}",
+                            escape_string(k, options),
+                            v.to_json_with(options)
+                        )
+                    })
+                    .collect();
+                format!("{{{}}}", items.join(","))
+            }
+        }
+    }
+
+    pub fn to_json_pretty(&self, indent: usize) -> String {
+        self.to_json_pretty_with(indent, &SerializeOptions::default())
+    }
+
+    pub fn to_json_pretty_with(&self, indent: usize, options: &SerializeOptions) -> String {
+        let body = self.to_json_pretty_at(indent, 0, options);
+        if options.trailing_newline {
+            format!("{}\n", body)
+        } else {
+            body
+        }
+    }
+
+    fn to_json_pretty_at(&self, indent: usize, depth: usize, options: &SerializeOptions) -> String {
+        let pad = " ".repeat(indent * (depth + 1));
+        let close_pad = " ".repeat(indent * depth);
+        match self {
+            Value::Array(values) if !values.is_empty() => {
+                let items: Vec<String> = values
+                    .iter()
+                    .map(|v| {
+                        format!(
+                            "{}{}",
+                            pad,
+                            v.to_json_pretty_at(indent, depth + 1, options)
+                        )
+                    })
+                    .collect();
+                format!("[\n{}\n{}]", items.join(",\n"), close_pad)
+            }
+            Value::Object(pairs) if !pairs.is_empty() => {
+                let items: Vec<String> = pairs
+                    .iter()
+                    .map(|(k, v)| {
+                        format!(
+                            "{}\"{}\": {}",
+                            pad,
+                            escape_string(k, options),
+                            v.to_json_pretty_at(indent, depth + 1, options)
+                        )
+                    })
+                    .collect();
+                format!("{{\n{}\n{}}}", items.join(",\n"), close_pad)
+            }
+            _ => self.to_json_with(options),
+        }
+    }
+}
+
+/// JSON has no token for NaN/Infinity, so non-finite numbers serialize as
+/// `null` rather than the invalid `NaN`/`inf`/`-inf` text `f64::to_string`
+/// would otherwise produce.
+fn format_number(n: f64) -> String {
+    if n.is_finite() {
+        n.to_string()
+    } else {
+        String::from("null")
+    }
+}
+
+fn escape_string(s: &str, options: &SerializeOptions) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c if options.escape_non_ascii && !c.is_ascii() => {
+                let mut buf = [0u16; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    escaped.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    UnexpectedChar,
+    UnterminatedString,
+    MalformedNumber,
+    MalformedEscapeSequence,
+    UnexpectedToken {
+        expected: TokenType,
+        actual: TokenType,
+    },
+    TrailingComma,
+    UnclosedObject,
+    UnclosedArray,
+    UnexpectedEof,
+    TrailingContent,
+}
+
 // pair -> string ":" value
 // value -> string | number | "null" | "true" | "false" | object | array
 // object -> "{" (pair ",")* "}"
 // array -> "[" (literal ",")* "]"
-pub struct Parser<'a> {
-    tokens: &'a Vec<Token>,
-    current: usize,
-    has_error: bool,
+pub struct Parser {
+    tokens: TokenIterator,
+    previous: Option<Token>,
+    errors: Vec<Error>,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(tokens: &Vec<Token>) -> Parser {
+impl Parser {
+    pub fn new(tokens: TokenIterator) -> Parser {
         Parser {
             tokens,
-            current: 0,
-            has_error: false,
+            previous: None,
+            errors: Vec::new(),
         }
     }
 
     pub fn parse(&mut self) -> Value {
-        self.expression()
+        let value = self.expression();
+        if !self.check(&TokenType::Eof) {
+            let span = self.peek().span;
+            self.push_error(ErrorKind::TrailingContent, span);
+        }
+        value
+    }
+
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        let mut errors = self.tokens.take_errors();
+        errors.extend(std::mem::take(&mut self.errors));
+        errors
+    }
+
+    fn push_error(&mut self, kind: ErrorKind, span: Span) {
+        self.errors.push(Error { kind, span });
+    }
+
+    fn expect(&mut self, token_type: TokenType) -> Result<Token, Error> {
+        if self.check(&token_type) {
+            Ok(self.advance().clone())
+        } else {
+            Err(Error {
+                kind: ErrorKind::UnexpectedToken {
+                    expected: token_type,
+                    actual: self.peek().token_type,
+                },
+                span: self.peek().span,
+            })
+        }
+    }
+
+    fn synchronize(&mut self) {
+        while !self.is_at_end() {
+            match self.peek().token_type {
+                TokenType::Comma => {
+                    self.advance();
+                    return;
+                }
+                TokenType::RightCurlyBracket | TokenType::RightSquareBracket => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
     }
 
     fn expression(&mut self) -> Value {
@@ -216,7 +612,22 @@ impl<'a> Parser<'a> {
         if self.matches(Box::new([TokenType::LeftSquareBracket])) {
             return self.array();
         }
-        report("Unrecognized value", &mut self.has_error);
+        let actual = self.peek().token_type;
+        let span = self.peek().span;
+        if actual == TokenType::Eof {
+            self.push_error(ErrorKind::UnexpectedEof, span);
+        } else {
+            self.push_error(
+                ErrorKind::UnexpectedToken {
+                    expected: TokenType::String,
+                    actual,
+                },
+                span,
+            );
+            // Consume the offending token so callers looping on "not a
+            // closing bracket yet" (e.g. `array`) always make progress.
+            self.advance();
+        }
         return Value::Null;
     }
 
@@ -227,11 +638,19 @@ impl<'a> Parser<'a> {
             let key_string = match key {
                 Value::String(s) => s,
                 _ => {
-                    report("Something went wrong.", &mut self.has_error);
+                    self.push_error(
+                        ErrorKind::UnexpectedToken {
+                            expected: TokenType::String,
+                            actual: self.previous().token_type,
+                        },
+                        self.previous().span,
+                    );
                     String::from("")
                 }
             };
-            self.advance();
+            if let Err(e) = self.expect(TokenType::Colon) {
+                self.push_error(e.kind, e.span);
+            }
             let value = self.expression();
             pairs.insert(key_string, value);
             if self.check(&TokenType::Comma) {
@@ -242,14 +661,16 @@ impl<'a> Parser<'a> {
             if self.matches(Box::new([TokenType::RightCurlyBracket])) {
                 return Value::Object(pairs);
             } else {
-                report("Unclosed curly brackets.", &mut self.has_error);
+                let span = self.peek().span;
+                self.push_error(ErrorKind::UnclosedObject, span);
+                self.synchronize();
             }
         } else {
-            report("Unexpected comma.", &mut self.has_error)
+            self.push_error(ErrorKind::TrailingComma, self.previous().span);
+            self.synchronize();
         }
-        println!("has_error: {}", self.has_error);
 
-        return Value::Object(HashMap::new());
+        return Value::Object(pairs);
     }
 
     fn array(&mut self) -> Value {
@@ -265,13 +686,16 @@ impl<'a> Parser<'a> {
             if self.matches(Box::new([TokenType::RightSquareBracket])) {
                 return Value::Array(values);
             } else {
-                report("Unclosed square brackets.", &mut self.has_error);
+                let span = self.peek().span;
+                self.push_error(ErrorKind::UnclosedArray, span);
+                self.synchronize();
             }
         } else {
-            report("Unexpected comma.", &mut self.has_error)
+            self.push_error(ErrorKind::TrailingComma, self.previous().span);
+            self.synchronize();
         }
 
-        return Value::Array(Vec::new());
+        return Value::Array(values);
     }
 
     fn matches(&mut self, token_types: Box<[TokenType]>) -> bool {
@@ -284,41 +708,53 @@ impl<'a> Parser<'a> {
         false
     }
 
-    fn check(&self, token_type: &TokenType) -> bool {
-        if self.is_at_end() {
-            return false;
-        }
-
+    fn check(&mut self, token_type: &TokenType) -> bool {
         self.peek().token_type == *token_type
     }
 
     fn advance(&mut self) -> &Token {
-        if !self.is_at_end() {
-            self.current = self.current + 1;
-        }
+        let token = self.tokens.advance();
+        self.previous = Some(token);
         self.previous()
     }
 
-    fn is_at_end(&self) -> bool {
+    fn is_at_end(&mut self) -> bool {
         self.peek().token_type == TokenType::Eof
     }
 
-    fn peek(&self) -> &Token {
-        &self.tokens[self.current]
+    fn peek(&mut self) -> &Token {
+        self.tokens.peek()
     }
 
     fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
+        self.previous
+            .as_ref()
+            .expect("previous() called before any token was consumed")
     }
 }
 
+/// Prints an error's kind alongside the source line it occurred on and a
+/// `^` caret pointing at the offending column, using `Span::locate`.
+fn report_error(source: &str, error: &Error) {
+    let (line_text, col) = error.span.locate(source);
+    println!("{:?} at line {}:{}", error.kind, error.span.line, col);
+    println!("{}", line_text);
+    println!("{}^", " ".repeat(col.saturating_sub(1)));
+}
+
 pub fn run_file(path: &str) {
     let contents = fs::read_to_string(path).expect("Unable to read file");
-    let mut lexer = Lexer::new(contents);
-    let tokens = lexer.scan_tokens();
+    let tokens = TokenIterator::new(Lexer::new(contents.clone()));
     let mut parser = Parser::new(tokens);
     let value = parser.parse();
-    println!("{:?}", value);
+    let errors = parser.take_errors();
+    if errors.is_empty() {
+        println!("{:?}", value);
+    } else {
+        for error in &errors {
+            report_error(&contents, error);
+        }
+    }
 }
 
 pub fn run_prompt() {
@@ -328,17 +764,121 @@ pub fn run_prompt() {
             .read_line(&mut prompt)
             .expect("Failed to read line");
 
-        let mut lexer = Lexer::new(prompt.to_owned());
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(prompt.clone()));
         let mut parser = Parser::new(tokens);
         let expression = parser.parse();
-        println!("{:?}", expression);
+        let errors = parser.take_errors();
+        if errors.is_empty() {
+            println!("{:?}", expression);
+        } else {
+            for error in &errors {
+                report_error(&prompt, error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod span {
+    use super::*;
+
+    #[test]
+    fn locate_points_at_multiline_error() {
+        let source = "[\n  1,\n  }\n]";
+        let tokens = TokenIterator::new(Lexer::new(String::from(source)));
+        let mut parser = Parser::new(tokens);
+        parser.parse();
+        let errors = parser.take_errors();
+        let error = errors.first().expect("expected an error");
+        let (line_text, col) = error.span.locate(source);
+        assert_eq!(line_text, "  }");
+        assert_eq!(col, 3);
+    }
+}
+
+#[cfg(test)]
+mod lexer_numbers {
+    use super::*;
+
+    fn lex_number(source: &str) -> Token {
+        let mut lexer = Lexer::new(String::from(source));
+        lexer.next_token()
+    }
+
+    #[test]
+    fn negative_integer() {
+        let token = lex_number("-5");
+        assert_eq!(token.token_type, TokenType::Number);
+        assert_eq!(token.literal, Value::Number(-5.0));
+    }
+
+    #[test]
+    fn fractional() {
+        let token = lex_number("3.14");
+        assert_eq!(token.token_type, TokenType::Number);
+        assert_eq!(token.literal, Value::Number(3.14));
+    }
+
+    #[test]
+    fn exponent() {
+        let token = lex_number("1e10");
+        assert_eq!(token.token_type, TokenType::Number);
+        assert_eq!(token.literal, Value::Number(1e10));
+    }
+
+    #[test]
+    fn negative_fraction_with_signed_exponent() {
+        let token = lex_number("-2.5e-3");
+        assert_eq!(token.token_type, TokenType::Number);
+        assert_eq!(token.literal, Value::Number(-2.5e-3));
+    }
+
+    #[test]
+    fn malformed_trailing_exponent_reports_error() {
+        let mut lexer = Lexer::new(String::from("1e"));
+        lexer.next_token();
+        let errors = lexer.take_errors();
+        assert!(errors.iter().any(|e| e.kind == ErrorKind::MalformedNumber));
     }
 }
 
-pub fn report(e: &str, has_error: &mut bool) {
-    *has_error = true;
-    println!("{e}");
+#[cfg(test)]
+mod lexer_strings {
+    use super::*;
+
+    fn lex_string(source: &str) -> Token {
+        let mut lexer = Lexer::new(String::from(source));
+        lexer.next_token()
+    }
+
+    #[test]
+    fn basic_escapes() {
+        let token = lex_string("\"a\\nb\\tc\"");
+        assert_eq!(token.token_type, TokenType::String);
+        assert_eq!(token.literal, Value::String(String::from("a\nb\tc")));
+    }
+
+    #[test]
+    fn unicode_escape() {
+        let token = lex_string("\"\\u00e9\"");
+        assert_eq!(token.literal, Value::String(String::from("\u{e9}")));
+    }
+
+    #[test]
+    fn surrogate_pair_escape() {
+        let token = lex_string("\"\\ud83d\\ude00\"");
+        assert_eq!(token.literal, Value::String(String::from("\u{1f600}")));
+    }
+
+    #[test]
+    fn unknown_escape_reports_error() {
+        let mut lexer = Lexer::new(String::from("\"\\q\""));
+        lexer.next_token();
+        let errors = lexer.take_errors();
+        assert!(errors
+            .iter()
+            .any(|e| e.kind == ErrorKind::MalformedEscapeSequence));
+    }
 }
 
 #[cfg(test)]
@@ -348,45 +888,28 @@ mod step_1 {
     #[test]
     fn valid() {
         let contents = fs::read_to_string("tests/step1/valid.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
-        let mut token_iter = tokens.iter();
+        let mut tokens = TokenIterator::new(Lexer::new(contents));
 
-        assert_eq!(
-            token_iter.next().unwrap().token_type,
-            TokenType::LeftCurlyBracket
-        );
-        assert_eq!(
-            token_iter.next().unwrap().token_type,
-            TokenType::RightCurlyBracket
-        );
+        assert_eq!(tokens.advance().token_type, TokenType::LeftCurlyBracket);
+        assert_eq!(tokens.advance().token_type, TokenType::RightCurlyBracket);
     }
 
     #[test]
     fn valid_2() {
         let contents = fs::read_to_string("tests/step1/valid2.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
-        let mut token_iter = tokens.iter();
+        let mut tokens = TokenIterator::new(Lexer::new(contents));
 
-        assert_eq!(
-            token_iter.next().unwrap().token_type,
-            TokenType::LeftCurlyBracket
-        );
-        assert_eq!(
-            token_iter.next().unwrap().token_type,
-            TokenType::RightCurlyBracket
-        );
+        assert_eq!(tokens.advance().token_type, TokenType::LeftCurlyBracket);
+        assert_eq!(tokens.advance().token_type, TokenType::RightCurlyBracket);
     }
 
     #[test]
     fn invalid() {
         let contents = fs::read_to_string("tests/step1/invalid.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         parser.parse();
-        assert!(parser.has_error)
+        assert!(!parser.take_errors().is_empty())
     }
 }
 
@@ -398,8 +921,7 @@ mod step_2 {
     #[test]
     fn valid() {
         let contents = fs::read_to_string("tests/step2/valid.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         let value = parser.parse();
 
@@ -415,8 +937,7 @@ mod step_2 {
     #[test]
     fn valid_2() {
         let contents = fs::read_to_string("tests/step2/valid2.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         let value = parser.parse();
 
@@ -438,22 +959,20 @@ mod step_2 {
     #[test]
     fn invalid() {
         let contents = fs::read_to_string("tests/step2/invalid.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         parser.parse();
-        assert!(parser.has_error);
+        assert!(!parser.take_errors().is_empty());
     }
 
     #[test]
     fn invalid_2() {
         let contents =
             fs::read_to_string("tests/step2/invalid2.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         parser.parse();
-        assert!(parser.has_error);
+        assert!(!parser.take_errors().is_empty());
     }
 }
 
@@ -464,8 +983,7 @@ mod step_3 {
     #[test]
     fn valid() {
         let contents = fs::read_to_string("tests/step3/valid.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         let value = parser.parse();
 
@@ -487,7 +1005,7 @@ mod step_3 {
                 );
 
                 assert!(o.contains_key("key5"));
-                assert_eq!(*o.get("key5").unwrap(), Value::Number(101));
+                assert_eq!(*o.get("key5").unwrap(), Value::Number(101.0));
             }
             _ => panic!(),
         }
@@ -496,11 +1014,10 @@ mod step_3 {
     #[test]
     fn invalid() {
         let contents = fs::read_to_string("tests/step3/invalid.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         parser.parse();
-        assert!(parser.has_error);
+        assert!(!parser.take_errors().is_empty());
     }
 }
 
@@ -511,8 +1028,7 @@ mod step_4 {
     #[test]
     fn valid() {
         let contents = fs::read_to_string("tests/step4/valid.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         let value = parser.parse();
 
@@ -522,7 +1038,7 @@ mod step_4 {
                 assert_eq!(*o.get("key").unwrap(), Value::String(String::from("value")));
 
                 assert!(o.contains_key("key-n"));
-                assert_eq!(*o.get("key-n").unwrap(), Value::Number(101));
+                assert_eq!(*o.get("key-n").unwrap(), Value::Number(101.0));
 
                 assert!(o.contains_key("key-o"));
                 assert_eq!(*o.get("key-o").unwrap(), Value::Object(HashMap::new()));
@@ -537,8 +1053,7 @@ mod step_4 {
     #[test]
     fn valid_2() {
         let contents = fs::read_to_string("tests/step4/valid2.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        let tokens = lexer.scan_tokens();
+        let tokens = TokenIterator::new(Lexer::new(contents));
         let mut parser = Parser::new(tokens);
         let value = parser.parse();
 
@@ -548,7 +1063,7 @@ mod step_4 {
                 assert_eq!(*o.get("key").unwrap(), Value::String(String::from("value")));
 
                 assert!(o.contains_key("key-n"));
-                assert_eq!(*o.get("key-n").unwrap(), Value::Number(101));
+                assert_eq!(*o.get("key-n").unwrap(), Value::Number(101.0));
 
                 assert!(o.contains_key("key-o"));
                 let inner_o = o.get("key-o").unwrap();
@@ -580,8 +1095,172 @@ mod step_4 {
     #[test]
     fn invalid() {
         let contents = fs::read_to_string("tests/step4/invalid.json").expect("Unable to read file");
-        let mut lexer = Lexer::new(contents);
-        lexer.scan_tokens();
-        assert!(lexer.has_error);
+        let mut tokens = TokenIterator::new(Lexer::new(contents));
+        while tokens.advance().token_type != TokenType::Eof {}
+        assert!(!tokens.take_errors().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod step_5 {
+    use super::*;
+
+    #[test]
+    fn malformed_number_reports_multiple_diagnostics() {
+        let contents = fs::read_to_string("tests/step5/malformed_number.json")
+            .expect("Unable to read file");
+        let tokens = TokenIterator::new(Lexer::new(contents));
+        let mut parser = Parser::new(tokens);
+        parser.parse();
+        let errors = parser.take_errors();
+        assert!(errors.len() >= 2, "expected more than one diagnostic, got {:?}", errors);
+        assert!(errors.iter().any(|e| e.kind == ErrorKind::UnexpectedChar));
+        assert!(errors.iter().any(|e| e.kind == ErrorKind::TrailingContent));
+    }
+
+    #[test]
+    fn trailing_content_after_object() {
+        let contents = fs::read_to_string("tests/step5/trailing_object.json")
+            .expect("Unable to read file");
+        let tokens = TokenIterator::new(Lexer::new(contents));
+        let mut parser = Parser::new(tokens);
+        parser.parse();
+        let errors = parser.take_errors();
+        assert!(!errors.is_empty(), "trailing garbage should be reported");
+    }
+
+    #[test]
+    fn trailing_content_after_literal() {
+        let contents = fs::read_to_string("tests/step5/trailing_literal.json")
+            .expect("Unable to read file");
+        let tokens = TokenIterator::new(Lexer::new(contents));
+        let mut parser = Parser::new(tokens);
+        parser.parse();
+        let errors = parser.take_errors();
+        assert!(errors.iter().any(|e| e.kind == ErrorKind::TrailingContent));
+    }
+
+    #[test]
+    fn array_with_unrecognized_element_does_not_hang() {
+        let tokens = TokenIterator::new(Lexer::new(String::from("[}]")));
+        let mut parser = Parser::new(tokens);
+        let value = parser.parse();
+        assert_eq!(value, Value::Array(vec![Value::Null]));
+        assert!(!parser.take_errors().is_empty());
+    }
+
+    #[test]
+    fn array_with_bare_colon_does_not_hang() {
+        let tokens = TokenIterator::new(Lexer::new(String::from("[:]")));
+        let mut parser = Parser::new(tokens);
+        let value = parser.parse();
+        assert_eq!(value, Value::Array(vec![Value::Null]));
+        assert!(!parser.take_errors().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod serializer {
+    use super::*;
+
+    fn parse(source: &str) -> Value {
+        let mut parser = Parser::new(TokenIterator::new(Lexer::new(String::from(source))));
+        parser.parse()
+    }
+
+    fn round_trips(value: Value) {
+        assert_eq!(parse(&value.to_json()), value);
+        assert_eq!(parse(&value.to_json_pretty(2)), value);
+    }
+
+    #[test]
+    fn compact_object() {
+        let mut pairs = HashMap::new();
+        pairs.insert(String::from("key"), Value::String(String::from("value")));
+        let value = Value::Object(pairs);
+        assert_eq!(value.to_json(), "{\"key\":\"value\"}");
+    }
+
+    #[test]
+    fn pretty_flat_array() {
+        let value = Value::Array(vec![Value::Number(1.0), Value::Number(2.0)]);
+        assert_eq!(value.to_json_pretty(2), "[\n  1,\n  2\n]");
+    }
+
+    #[test]
+    fn escapes_special_characters_in_strings() {
+        let value = Value::String(String::from("a\n\"b\"\\c"));
+        assert_eq!(value.to_json(), "\"a\\n\\\"b\\\"\\\\c\"");
+    }
+
+    #[test]
+    fn round_trips_scalars() {
+        round_trips(Value::Null);
+        round_trips(Value::Bool(true));
+        round_trips(Value::Bool(false));
+        round_trips(Value::Number(101.0));
+        round_trips(Value::Number(-3.25));
+        round_trips(Value::String(String::from("hello\nworld")));
+    }
+
+    #[test]
+    fn round_trips_nested_structure() {
+        let mut inner = HashMap::new();
+        inner.insert(String::from("inner key"), Value::String(String::from("inner value")));
+
+        let mut outer = HashMap::new();
+        outer.insert(String::from("key"), Value::String(String::from("value")));
+        outer.insert(String::from("key-n"), Value::Number(101.0));
+        outer.insert(String::from("key-o"), Value::Object(inner));
+        outer.insert(
+            String::from("key-l"),
+            Value::Array(vec![Value::String(String::from("list value"))]),
+        );
+
+        round_trips(Value::Object(outer));
+    }
+
+    #[test]
+    fn escapes_non_ascii_when_requested() {
+        let value = Value::String(String::from("café日本語"));
+        let options = SerializeOptions {
+            escape_non_ascii: true,
+            ..SerializeOptions::default()
+        };
+        assert_eq!(
+            value.to_json_with(&options),
+            "\"caf\\u00e9\\u65e5\\u672c\\u8a9e\""
+        );
+        assert_eq!(value.to_json(), "\"café日本語\"");
+        assert_eq!(parse(&value.to_json_with(&options)), value);
+    }
+
+    #[test]
+    fn escapes_non_ascii_outside_bmp_as_surrogate_pair() {
+        let value = Value::String(String::from("\u{1f600}"));
+        let options = SerializeOptions {
+            escape_non_ascii: true,
+            ..SerializeOptions::default()
+        };
+        assert_eq!(value.to_json_with(&options), "\"\\ud83d\\ude00\"");
+        assert_eq!(parse(&value.to_json_with(&options)), value);
+    }
+
+    #[test]
+    fn pretty_trailing_newline_when_requested() {
+        let value = Value::Array(vec![Value::Number(1.0)]);
+        let options = SerializeOptions {
+            trailing_newline: true,
+            ..SerializeOptions::default()
+        };
+        assert_eq!(value.to_json_pretty_with(2, &options), "[\n  1\n]\n");
+        assert_eq!(value.to_json_pretty(2), "[\n  1\n]");
+    }
+
+    #[test]
+    fn non_finite_numbers_serialize_as_null() {
+        assert_eq!(Value::Number(f64::NAN).to_json(), "null");
+        assert_eq!(Value::Number(f64::INFINITY).to_json(), "null");
+        assert_eq!(Value::Number(f64::NEG_INFINITY).to_json(), "null");
     }
 }